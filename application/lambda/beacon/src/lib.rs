@@ -1,117 +1,625 @@
 use std::result;
 use std::sync::Arc;
 
-use aws_config::load_from_env;
-use aws_sdk_s3::types::ByteStream;
-use aws_sdk_s3::Client;
+use bytes::Bytes;
 use futures::stream::FuturesUnordered;
 use futures::{StreamExt, TryStreamExt};
+use htsget_search::htsget::bam_search::BamSearch;
+use htsget_search::htsget::bcf_search::BcfSearch;
+use htsget_search::htsget::cram_search::CramSearch;
 use htsget_search::htsget::search::Search;
 use htsget_search::htsget::vcf_search::VcfSearch;
-use htsget_search::htsget::Format::Vcf;
+use htsget_search::htsget::Format;
 use htsget_search::htsget::Query;
-use htsget_search::storage::aws::AwsS3Storage;
-use htsget_search::storage::{BytesPosition, BytesRange};
-use htsget_search::RegexResolver;
+use htsget_search::storage::BytesPosition;
 use lambda_runtime::{Error, LambdaEvent};
-use noodles::tabix::Index;
-use noodles::{bgzf, tabix, vcf};
+use noodles::cram::crai;
+use noodles::{bam, bcf, bgzf, core, cram, csi, sam, tabix, vcf};
 use noodles_vcf::record::{AlternateBases, Position, ReferenceBases};
 use noodles_vcf::Header;
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
 use tokio::select;
 
+mod storage;
+
+pub use storage::{BeaconStorage, KeyNotFound, LocalStorage, S3Storage, S3StorageConfig};
+
 pub type Result<T> = result::Result<T, Error>;
 
+/// The genomic file format targeted by a sequence query, and the index
+/// format that goes along with it.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SequenceFormat {
+    Vcf,
+    Bcf,
+    Bam,
+    Cram,
+}
+
+impl SequenceFormat {
+    /// The suffix a data key is expected to carry for this format.
+    fn key_suffix(&self) -> &'static str {
+        match self {
+            SequenceFormat::Vcf => ".vcf.gz",
+            SequenceFormat::Bcf => ".bcf",
+            SequenceFormat::Bam => ".bam",
+            SequenceFormat::Cram => ".cram",
+        }
+    }
+
+    /// The suffix the companion index key is expected to carry for this format.
+    fn index_suffix(&self) -> &'static str {
+        match self {
+            SequenceFormat::Vcf => ".vcf.gz.tbi",
+            SequenceFormat::Bcf => ".bcf.csi",
+            SequenceFormat::Bam => ".bam.csi",
+            SequenceFormat::Cram => ".cram.crai",
+        }
+    }
+
+    /// Infer the format from a data key's suffix, falling back to an error
+    /// if none of the supported suffixes match.
+    fn from_key(key: &str) -> Result<Self> {
+        for format in [
+            SequenceFormat::Vcf,
+            SequenceFormat::Bcf,
+            SequenceFormat::Bam,
+            SequenceFormat::Cram,
+        ] {
+            if key.ends_with(format.key_suffix()) {
+                return Ok(format);
+            }
+        }
+
+        Err(Error::from(format!("Unsupported key format: {}", key)))
+    }
+
+    fn htsget_format(&self) -> Format {
+        match self {
+            SequenceFormat::Vcf => Format::Vcf,
+            SequenceFormat::Bcf => Format::Bcf,
+            SequenceFormat::Bam => Format::Bam,
+            SequenceFormat::Cram => Format::Cram,
+        }
+    }
+}
+
 /// A beacon sequence query request, see:
 /// http://docs.genomebeacons.org/variant-queries/
 #[derive(Debug, Deserialize)]
 pub struct SequenceQueryRequest {
     vcf_bucket: String,
-    // This is assumed to be GZ compressed, i.e. ending in ".vcf.gz"
+    // The suffix is expected to match `format`, e.g. ".vcf.gz" or ".bcf".
     vcf_key: String,
     vcf_index_bucket: String,
-    // This is assumed to end in ".vcf.gz.tbi"
+    // The suffix is expected to match `format`'s index, e.g. ".vcf.gz.tbi" or ".bcf.csi".
     vcf_index_key: String,
+    // Defaults to `Vcf` for backwards compatibility with existing callers.
+    #[serde(default)]
+    format: Option<SequenceFormat>,
     reference_name: String,
     start: u32,
+    // Inclusive end of a range query. When set, every matching record in `[start, end]` is
+    // returned instead of a single point match at `start`.
+    #[serde(default)]
+    end: Option<u32>,
+    // Required for a point query (`end` unset); act as optional filters for a range query.
+    #[serde(default)]
+    reference_bases: Option<String>,
+    #[serde(default)]
+    alternate_bases: Option<String>,
+    // One of "SNV", "MNV", "INS", "DEL". Filters range query matches by variant shape;
+    // ignored for point queries.
+    #[serde(default)]
+    variant_type: Option<String>,
+    // When set, also report the carrier/call counts across the file's samples at the
+    // matched site, instead of only whether the variant exists. Only applies to point queries.
+    #[serde(default)]
+    count: bool,
+}
+
+/// A single matching record from a range query, see:
+/// http://docs.genomebeacons.org/variant-queries/
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantMatch {
+    position: u32,
     reference_bases: String,
     alternate_bases: String,
 }
 
-/// The beacon response, indicating whether the sequence was found.
-#[derive(Debug, Serialize)]
+/// The beacon response, indicating whether the sequence was found, along with the carrier
+/// and call counts across samples when the request asked for `count`, the full list of
+/// matches when the request was a range query, and a pre-signed handover URL to the matched
+/// byte range, when the storage backend supports presigning. `error` is only ever set on a
+/// result inside a `BatchSequenceQueryResponse`, where one query's failure (an unsupported
+/// combination, a missing file) shouldn't take down the rest of the batch.
+#[derive(Debug, Clone, Serialize)]
 pub struct SequenceQueryResponse {
     found: bool,
+    variant_count: Option<u32>,
+    call_count: Option<u32>,
+    matches: Option<Vec<VariantMatch>>,
+    handover: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The carrier ("variant") and non-missing ("call") counts across a matched site's samples.
+#[derive(Debug, Clone, Copy, Default)]
+struct MatchCounts {
+    variant_count: u32,
+    call_count: u32,
+}
+
+/// A point-query match, carrying counts when the format has samples to count across. BAM/CRAM
+/// records have no per-sample genotypes, so a match there is always `Uncounted` rather than a
+/// fabricated all-zero `MatchCounts`.
+#[derive(Debug, Clone, Copy)]
+enum SequenceMatch {
+    Counted(MatchCounts),
+    Uncounted,
+}
+
+impl SequenceQueryResponse {
+    fn not_found() -> Self {
+        Self {
+            found: false,
+            variant_count: None,
+            call_count: None,
+            matches: None,
+            handover: None,
+            error: None,
+        }
+    }
+
+    fn found(counts: Option<MatchCounts>, handover: Option<String>) -> Self {
+        Self {
+            found: true,
+            variant_count: counts.map(|counts| counts.variant_count),
+            call_count: counts.map(|counts| counts.call_count),
+            matches: None,
+            handover,
+            error: None,
+        }
+    }
+
+    fn range(matches: Vec<VariantMatch>) -> Self {
+        Self {
+            found: !matches.is_empty(),
+            variant_count: None,
+            call_count: None,
+            matches: Some(matches),
+            handover: None,
+            error: None,
+        }
+    }
+
+    /// A per-query failure inside a batch response. Kept distinct from the top-level `Result`
+    /// the single-query handler returns, so one query's failure doesn't take the rest of a
+    /// `BatchSequenceQueryResponse` down with it.
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            found: false,
+            variant_count: None,
+            call_count: None,
+            matches: None,
+            handover: None,
+            error: Some(message.into()),
+        }
+    }
+}
+
+/// The index needed to compute byte ranges, one variant per supported format.
+enum SequenceIndex {
+    Tabix(tabix::Index),
+    Csi(csi::Index),
+    Crai(crai::Index),
+}
+
+/// The header needed to resolve reference names and read records, one variant per header kind
+/// the supported formats use. VCF/BCF carry a `noodles_vcf::Header`; BAM/CRAM carry a
+/// `noodles::sam::Header` instead, since alignment records have no notion of VCF INFO/FORMAT
+/// metadata.
+enum SequenceHeader {
+    Vcf(Header),
+    Sam(sam::Header),
 }
 
-/// Handles the SequenceQueryRequest lambda event.
-pub async fn beacon_handler(
+/// Handles the SequenceQueryRequest lambda event against a given storage backend.
+pub async fn beacon_handler<S: BeaconStorage>(
     event: LambdaEvent<SequenceQueryRequest>,
+    storage: S,
 ) -> Result<SequenceQueryResponse> {
-    let vcf_index_id = verify_key(&event.payload.vcf_index_key, ".vcf.gz.tbi")?;
-    let vcf_id = verify_key(&event.payload.vcf_key, ".vcf.gz")?;
+    let format = event
+        .payload
+        .format
+        .unwrap_or(SequenceFormat::from_key(&event.payload.vcf_key)?);
 
-    let client = Client::new(&load_from_env().await);
+    let vcf_index_id = verify_key(&event.payload.vcf_index_key, format.index_suffix())?;
+    let vcf_id = verify_key(&event.payload.vcf_key, format.key_suffix())?;
 
     let index = get_index(
-        &client,
+        &storage,
         &event.payload.vcf_index_bucket,
         &event.payload.vcf_index_key,
+        format,
     )
     .await?;
 
-    let vcf_search = vcf_searcher(client.clone(), event.payload.vcf_bucket.clone());
-    let header = vcf_search.get_header(&vcf_id, &Vcf, &index).await?;
+    let mut query = Query::new(&vcf_index_id, format.htsget_format()).with_start(event.payload.start);
+    if let Some(end) = event.payload.end {
+        query = query.with_end(end);
+    }
 
-    let byte_ranges = vcf_search
-        .get_byte_ranges_for_reference_name(
-            event.payload.reference_name,
-            &index,
-            &header,
-            Query::new(&vcf_index_id, Vcf).with_start(event.payload.start),
-        )
-        .await?;
+    let header = get_header(&storage, event.payload.vcf_bucket.clone(), &vcf_id, format, &index).await?;
+    let byte_ranges = get_byte_ranges(
+        &storage,
+        event.payload.vcf_bucket.clone(),
+        event.payload.reference_name,
+        &index,
+        &header,
+        &vcf_id,
+        format,
+        query,
+    )
+    .await?;
 
-    let mut blocks = FuturesUnordered::new();
-    for range in BytesPosition::merge_all(byte_ranges)
-        .iter()
-        .map(BytesRange::from)
-    {
-        let client_owned = client.clone();
-        let bucket = event.payload.vcf_bucket.clone();
-        let key = event.payload.vcf_key.clone();
-        blocks.push(tokio::spawn(async move {
-            client_owned
-                .get_object()
-                .bucket(bucket)
-                .key(key)
-                .range(String::from(&range))
-                .send()
-                .await
-        }));
+    let mut blocks = download_ranges(&storage, &event.payload.vcf_bucket, &event.payload.vcf_key, byte_ranges);
+
+    if let Some(end) = event.payload.end {
+        let mut matches = Vec::new();
+
+        loop {
+            select! {
+                Some(next) = blocks.next() => {
+                    let (_, block) = next??;
+                    matches.extend(
+                        beacon_range_query(
+                            std::io::Cursor::new(block),
+                            &header,
+                            format,
+                            event.payload.start,
+                            end,
+                            event.payload.reference_bases.as_deref(),
+                            event.payload.alternate_bases.as_deref(),
+                            event.payload.variant_type.as_deref(),
+                        )
+                        .await?,
+                    );
+                },
+                else => break
+            }
+        }
+
+        // Overlapping merged byte ranges can hand the same record back from more than one
+        // downloaded block, so sort and dedupe on the full match identity before returning.
+        matches.sort_by(|a, b| {
+            (a.position, &a.reference_bases, &a.alternate_bases)
+                .cmp(&(b.position, &b.reference_bases, &b.alternate_bases))
+        });
+        matches.dedup_by(|a, b| {
+            a.position == b.position
+                && a.reference_bases == b.reference_bases
+                && a.alternate_bases == b.alternate_bases
+        });
+        return Ok(SequenceQueryResponse::range(matches));
     }
 
+    let reference_bases = event
+        .payload
+        .reference_bases
+        .as_deref()
+        .ok_or_else(|| Error::from("reference_bases is required for a point query"))?;
+    let alternate_bases = event
+        .payload
+        .alternate_bases
+        .as_deref()
+        .ok_or_else(|| Error::from("alternate_bases is required for a point query"))?;
+
     loop {
         select! {
             Some(next) = blocks.next() => {
-                if beacon_sequence_query(
-                    next??.body,
+                let (range, block) = next??;
+                if let Some(sequence_match) = beacon_sequence_query(
+                    std::io::Cursor::new(block),
                     &header,
+                    format,
                     event.payload.start,
-                    &event.payload.reference_bases,
-                    &event.payload.alternate_bases,
+                    reference_bases,
+                    alternate_bases,
                 )
                 .await?
                 {
-                    return Ok(SequenceQueryResponse { found: true });
+                    let counts = match sequence_match {
+                        SequenceMatch::Counted(counts) => event.payload.count.then_some(counts),
+                        SequenceMatch::Uncounted => None,
+                    };
+                    let handover = build_handover(
+                        &storage,
+                        &event.payload.vcf_bucket,
+                        &event.payload.vcf_key,
+                        &range,
+                    )
+                    .await?;
+                    return Ok(SequenceQueryResponse::found(counts, handover));
                 }
             },
             else => break
         }
     }
 
-    Ok(SequenceQueryResponse { found: false })
+    Ok(SequenceQueryResponse::not_found())
+}
+
+/// Spawn one download per merged byte range, returning each range alongside its downloaded
+/// bytes as they complete, so a caller that matches inside a block can later presign a
+/// handover URL for the exact range it came from.
+fn download_ranges<S: BeaconStorage>(
+    storage: &S,
+    bucket: &str,
+    key: &str,
+    byte_ranges: Vec<BytesPosition>,
+) -> FuturesUnordered<tokio::task::JoinHandle<Result<(BytesPosition, Bytes)>>> {
+    let blocks = FuturesUnordered::new();
+    for range in BytesPosition::merge_all(byte_ranges) {
+        let storage = storage.clone();
+        let bucket = bucket.to_string();
+        let key = key.to_string();
+        let start = range.get_start().unwrap_or(0);
+        let end = range.get_end();
+        blocks.push(tokio::spawn(async move {
+            let bytes = storage.get_range(&bucket, &key, start, end).await?;
+            Ok((range, bytes))
+        }));
+    }
+    blocks
+}
+
+/// Build a pre-signed handover URL for the byte range containing a matched record, if the
+/// storage backend supports presigning. Guards against presigning a key that doesn't exist.
+async fn build_handover<S: BeaconStorage>(
+    storage: &S,
+    bucket: &str,
+    key: &str,
+    range: &BytesPosition,
+) -> Result<Option<String>> {
+    if !storage.key_exists(bucket, key).await? {
+        return Err(KeyNotFound {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        }
+        .into());
+    }
+
+    storage
+        .presigned_range_url(bucket, key, range.get_start().unwrap_or(0), range.get_end())
+        .await
+}
+
+/// A batch of sequence queries answered within a single Lambda invocation. Queries that
+/// target the same VCF/BCF/BAM/CRAM file share a single `get_index`/`get_header` round-trip,
+/// and their required byte ranges are merged so each overlapping S3 block is only downloaded
+/// once. See: http://docs.genomebeacons.org/variant-queries/
+#[derive(Debug, Deserialize)]
+pub struct BatchSequenceQueryRequest {
+    queries: Vec<SequenceQueryRequest>,
+}
+
+/// The response to a `BatchSequenceQueryRequest`, with one result per input query, in order.
+#[derive(Debug, Serialize)]
+pub struct BatchSequenceQueryResponse {
+    results: Vec<SequenceQueryResponse>,
+}
+
+/// Handles the BatchSequenceQueryRequest lambda event against a given storage backend.
+pub async fn beacon_batch_handler<S: BeaconStorage>(
+    event: LambdaEvent<BatchSequenceQueryRequest>,
+    storage: S,
+) -> Result<BatchSequenceQueryResponse> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, query) in event.payload.queries.iter().enumerate() {
+        let group = groups
+            .iter_mut()
+            .find(|indices| is_same_file(&event.payload.queries[indices[0]], query));
+
+        match group {
+            Some(indices) => indices.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+
+    let mut results = vec![SequenceQueryResponse::not_found(); event.payload.queries.len()];
+    for indices in groups {
+        let queries: Vec<_> = indices.iter().map(|&i| &event.payload.queries[i]).collect();
+
+        // A single group failing (an unsupported combination, a missing file) shouldn't wipe
+        // out results already computed for every other group in the batch, so the error is
+        // recorded against just this group's indices instead of propagated with `?`.
+        match beacon_group_query(&storage, &queries).await {
+            Ok(group_results) => {
+                for (&i, sequence_match) in indices.iter().zip(group_results) {
+                    results[i] = match sequence_match {
+                        // Byte ranges are merged across the whole group in
+                        // `beacon_group_query`, so there's no single range left to presign a
+                        // handover for; batch responses never carry one.
+                        Some(SequenceMatch::Counted(counts)) => SequenceQueryResponse::found(
+                            event.payload.queries[i].count.then_some(counts),
+                            None,
+                        ),
+                        Some(SequenceMatch::Uncounted) => SequenceQueryResponse::found(None, None),
+                        None => SequenceQueryResponse::not_found(),
+                    };
+                }
+            }
+            Err(err) => {
+                for i in indices {
+                    results[i] = SequenceQueryResponse::error(err.to_string());
+                }
+            }
+        }
+    }
+
+    Ok(BatchSequenceQueryResponse { results })
+}
+
+/// The payload shape the Lambda actually receives: either a single `SequenceQueryRequest`, or a
+/// `BatchSequenceQueryRequest` wrapping many. Distinguished by the presence of the `queries`
+/// field, so callers don't need a separate Lambda entry point per shape.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BeaconRequest {
+    Batch(BatchSequenceQueryRequest),
+    Single(SequenceQueryRequest),
+}
+
+/// The response shape matching whichever `BeaconRequest` variant was handled.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BeaconResponse {
+    Batch(BatchSequenceQueryResponse),
+    Single(SequenceQueryResponse),
+}
+
+/// Dispatches a Lambda event to `beacon_handler` or `beacon_batch_handler`, depending on
+/// whether the payload is a single query or a batch of them. This is the handler the Lambda
+/// entry point actually wires up, so that batch mode is reachable from a deployed invocation.
+pub async fn beacon_dispatch_handler<S: BeaconStorage>(
+    event: LambdaEvent<BeaconRequest>,
+    storage: S,
+) -> Result<BeaconResponse> {
+    match event.payload {
+        BeaconRequest::Single(payload) => {
+            beacon_handler(LambdaEvent::new(payload, event.context), storage)
+                .await
+                .map(BeaconResponse::Single)
+        }
+        BeaconRequest::Batch(payload) => {
+            beacon_batch_handler(LambdaEvent::new(payload, event.context), storage)
+                .await
+                .map(BeaconResponse::Batch)
+        }
+    }
+}
+
+/// Whether two queries target the same underlying data and index files, the same reference
+/// sequence within it, and the same effective format. Two queries for the same path with
+/// conflicting explicit `format`s are kept in separate groups instead of silently taking the
+/// first query's format for both. Queries against different `reference_name`s are also kept
+/// separate: the point-query comparators only check `position`/`reference_bases`/
+/// `alternate_bases`, not the record's chromosome, so merging byte ranges across contigs would
+/// let a coincidentally identical position/REF/ALT on one contig answer a query for another.
+fn is_same_file(a: &SequenceQueryRequest, b: &SequenceQueryRequest) -> bool {
+    a.vcf_bucket == b.vcf_bucket
+        && a.vcf_key == b.vcf_key
+        && a.vcf_index_bucket == b.vcf_index_bucket
+        && a.vcf_index_key == b.vcf_index_key
+        && a.reference_name == b.reference_name
+        && effective_format(a) == effective_format(b)
+}
+
+/// The format a query resolves to: its explicit `format`, or one inferred from the data key's
+/// suffix. Resolves to `None` if neither is available, so a query with an unresolvable format
+/// never spuriously conflicts with another in `is_same_file` — `beacon_group_query` surfaces
+/// the real error once such a group actually runs.
+fn effective_format(query: &SequenceQueryRequest) -> Option<SequenceFormat> {
+    query.format.or_else(|| SequenceFormat::from_key(&query.vcf_key).ok())
+}
+
+/// Answer every query in a group that targets the same file, downloading each byte range
+/// the group needs only once, then testing every query against the blocks it overlaps.
+async fn beacon_group_query<S: BeaconStorage>(
+    storage: &S,
+    queries: &[&SequenceQueryRequest],
+) -> Result<Vec<Option<SequenceMatch>>> {
+    if queries.iter().any(|query| query.end.is_some()) {
+        return Err(Error::from("range queries are not supported in batch mode"));
+    }
+
+    let representative = queries[0];
+    let format = representative
+        .format
+        .unwrap_or(SequenceFormat::from_key(&representative.vcf_key)?);
+
+    let vcf_index_id = verify_key(&representative.vcf_index_key, format.index_suffix())?;
+    let vcf_id = verify_key(&representative.vcf_key, format.key_suffix())?;
+
+    let index = get_index(
+        storage,
+        &representative.vcf_index_bucket,
+        &representative.vcf_index_key,
+        format,
+    )
+    .await?;
+
+    // Every query in the group targets the same file, so the header only needs to be
+    // resolved once and reused across each query's byte-range lookup below.
+    let header = get_header(storage, representative.vcf_bucket.clone(), &vcf_id, format, &index).await?;
+
+    let mut merged_ranges = Vec::new();
+    for query in queries {
+        let htsget_query =
+            Query::new(&vcf_index_id, format.htsget_format()).with_start(query.start);
+
+        let byte_ranges = get_byte_ranges(
+            storage,
+            representative.vcf_bucket.clone(),
+            query.reference_name.clone(),
+            &index,
+            &header,
+            &vcf_id,
+            format,
+            htsget_query,
+        )
+        .await?;
+
+        merged_ranges.extend(byte_ranges);
+    }
+
+    let mut blocks = download_ranges(
+        storage,
+        &representative.vcf_bucket,
+        &representative.vcf_key,
+        merged_ranges,
+    );
+
+    let mut found = vec![None; queries.len()];
+    loop {
+        select! {
+            Some(next) = blocks.next() => {
+                let (_, block) = next??;
+
+                for (i, query) in queries.iter().enumerate() {
+                    if found[i].is_some() {
+                        continue;
+                    }
+
+                    let reference_bases = query
+                        .reference_bases
+                        .as_deref()
+                        .ok_or_else(|| Error::from("reference_bases is required for a point query"))?;
+                    let alternate_bases = query
+                        .alternate_bases
+                        .as_deref()
+                        .ok_or_else(|| Error::from("alternate_bases is required for a point query"))?;
+
+                    found[i] = beacon_sequence_query(
+                        std::io::Cursor::new(block.clone()),
+                        &header,
+                        format,
+                        query.start,
+                        reference_bases,
+                        alternate_bases,
+                    )
+                    .await?;
+                }
+            },
+            else => break
+        }
+    }
+
+    Ok(found)
 }
 
 /// Verify that the index key ends with the suffix.
@@ -123,31 +631,157 @@ fn verify_key(key: &str, suffix: &str) -> Result<String> {
     }
 }
 
-/// Get the index from the bucket and key.
-async fn get_index(client: &Client, bucket: &str, key: &str) -> Result<Index> {
-    let response = client.get_object().bucket(bucket).key(key).send().await?;
+/// Get the index from the bucket and key, using the reader appropriate for the format.
+async fn get_index<S: BeaconStorage>(
+    storage: &S,
+    bucket: &str,
+    key: &str,
+    format: SequenceFormat,
+) -> Result<SequenceIndex> {
+    let bytes = storage.get_index_bytes(bucket, key).await?;
+
+    Ok(match format {
+        SequenceFormat::Vcf => SequenceIndex::Tabix(
+            tabix::AsyncReader::new(std::io::Cursor::new(bytes))
+                .read_index()
+                .await?,
+        ),
+        SequenceFormat::Bcf | SequenceFormat::Bam => SequenceIndex::Csi(
+            csi::AsyncReader::new(std::io::Cursor::new(bytes))
+                .read_index()
+                .await?,
+        ),
+        SequenceFormat::Cram => SequenceIndex::Crai(
+            crai::AsyncReader::new(std::io::Cursor::new(bytes))
+                .read_index()
+                .await?,
+        ),
+    })
+}
+
+/// Look up the header for a format/index, dispatching to the searcher appropriate for the
+/// format. Fetching this is an S3 round-trip, so callers resolving byte ranges for several
+/// queries against the same file should call this once up front and pass the result to
+/// `get_byte_ranges` for each query, rather than re-fetching the header per query.
+async fn get_header<S: BeaconStorage>(
+    storage: &S,
+    bucket: String,
+    id: &str,
+    format: SequenceFormat,
+    index: &SequenceIndex,
+) -> Result<SequenceHeader> {
+    let htsget_storage = Arc::new(storage.htsget_storage(bucket)?);
+
+    Ok(match (format, index) {
+        (SequenceFormat::Vcf, SequenceIndex::Tabix(index)) => SequenceHeader::Vcf(
+            VcfSearch::new(htsget_storage)
+                .get_header(id, &Format::Vcf, index)
+                .await?,
+        ),
+        (SequenceFormat::Bcf, SequenceIndex::Csi(index)) => SequenceHeader::Vcf(
+            BcfSearch::new(htsget_storage)
+                .get_header(id, &Format::Bcf, index)
+                .await?,
+        ),
+        (SequenceFormat::Bam, SequenceIndex::Csi(index)) => SequenceHeader::Sam(
+            BamSearch::new(htsget_storage)
+                .get_header(id, &Format::Bam, index)
+                .await?,
+        ),
+        (SequenceFormat::Cram, SequenceIndex::Crai(index)) => SequenceHeader::Sam(
+            CramSearch::new(htsget_storage)
+                .get_header(id, &Format::Cram, index)
+                .await?,
+        ),
+        _ => unreachable!("format and index kind are always constructed together"),
+    })
+}
 
-    Ok(tabix::AsyncReader::new(response.body.into_async_read())
-        .read_index()
-        .await?)
+/// Look up the byte ranges for a reference name against an already-fetched header and index,
+/// dispatching to the searcher appropriate for the format.
+async fn get_byte_ranges<S: BeaconStorage>(
+    storage: &S,
+    bucket: String,
+    reference_name: String,
+    index: &SequenceIndex,
+    header: &SequenceHeader,
+    id: &str,
+    format: SequenceFormat,
+    query: Query,
+) -> Result<Vec<BytesPosition>> {
+    let htsget_storage = Arc::new(storage.htsget_storage(bucket)?);
+
+    Ok(match (format, index, header) {
+        (SequenceFormat::Vcf, SequenceIndex::Tabix(index), SequenceHeader::Vcf(header)) => {
+            VcfSearch::new(htsget_storage)
+                .get_byte_ranges_for_reference_name(reference_name, index, header, query)
+                .await?
+        }
+        (SequenceFormat::Bcf, SequenceIndex::Csi(index), SequenceHeader::Vcf(header)) => {
+            BcfSearch::new(htsget_storage)
+                .get_byte_ranges_for_reference_name(reference_name, index, header, query)
+                .await?
+        }
+        (SequenceFormat::Bam, SequenceIndex::Csi(index), SequenceHeader::Sam(header)) => {
+            BamSearch::new(htsget_storage)
+                .get_byte_ranges_for_reference_name(reference_name, index, header, query)
+                .await?
+        }
+        (SequenceFormat::Cram, SequenceIndex::Crai(index), SequenceHeader::Sam(header)) => {
+            CramSearch::new(htsget_storage)
+                .get_byte_ranges_for_reference_name(reference_name, index, header, query)
+                .await?
+        }
+        _ => unreachable!("format, index and header kind are always constructed together"),
+    })
 }
 
-/// Create the vcf search struct.
-fn vcf_searcher(client: Client, bucket: String) -> VcfSearch<AwsS3Storage> {
-    let storage = AwsS3Storage::new(client, bucket, RegexResolver::default());
-    VcfSearch::new(Arc::new(storage))
+/// Perform the beacon sequence query search, using the record comparator appropriate for the
+/// format. Returns the matched site's carrier/call counts, or `None` if no match was found.
+async fn beacon_sequence_query<R>(
+    reader: R,
+    header: &SequenceHeader,
+    format: SequenceFormat,
+    start: u32,
+    reference_bases: &str,
+    alternate_bases: &str,
+) -> Result<Option<SequenceMatch>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    match (format, header) {
+        (SequenceFormat::Vcf, SequenceHeader::Vcf(header)) => {
+            vcf_sequence_query(reader, header, start, reference_bases, alternate_bases).await
+        }
+        (SequenceFormat::Bcf, SequenceHeader::Vcf(header)) => {
+            bcf_sequence_query(reader, header, start, reference_bases, alternate_bases).await
+        }
+        (SequenceFormat::Bam, SequenceHeader::Sam(header))
+        | (SequenceFormat::Cram, SequenceHeader::Sam(header)) => {
+            // BAM/CRAM records carry alignments rather than VCF-style alleles, so a Beacon
+            // sequence match is defined the same way regardless of which of the two is used.
+            // Neither format carries per-sample genotypes, so a match here is never `Counted`.
+            Ok(
+                read_sequence_query(reader, format, header, start, reference_bases, alternate_bases)
+                    .await?
+                    .then_some(SequenceMatch::Uncounted),
+            )
+        }
+        _ => unreachable!("format and header kind are always constructed together"),
+    }
 }
 
-/// Perform the beacon sequence query search.
-async fn beacon_sequence_query(
-    byte_stream: ByteStream,
+async fn vcf_sequence_query<R>(
+    reader: R,
     header: &Header,
     start: u32,
     reference_bases: &str,
     alternate_bases: &str,
-) -> Result<bool> {
-    let mut vcf_blocks =
-        vcf::AsyncReader::new(bgzf::AsyncReader::new(byte_stream.into_async_read()));
+) -> Result<Option<SequenceMatch>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut vcf_blocks = vcf::AsyncReader::new(bgzf::AsyncReader::new(reader));
     let mut records = vcf_blocks.records(header);
 
     while let Some(record) = records.try_next().await? {
@@ -155,18 +789,379 @@ async fn beacon_sequence_query(
         let pos = record.position();
 
         if pos > start {
-            return Ok(false);
+            return Ok(None);
         }
 
         if pos == start
             && record.reference_bases() == &reference_bases.parse::<ReferenceBases>()?
             && record.alternate_bases() == &alternate_bases.parse::<AlternateBases>()?
         {
-            return Ok(true);
+            return Ok(Some(SequenceMatch::Counted(count_genotypes(&record)?)));
+        }
+    }
+
+    Ok(None)
+}
+
+async fn bcf_sequence_query<R>(
+    reader: R,
+    header: &Header,
+    start: u32,
+    reference_bases: &str,
+    alternate_bases: &str,
+) -> Result<Option<SequenceMatch>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut bcf_blocks = bcf::AsyncReader::new(reader);
+    let mut records = bcf_blocks.records(header);
+
+    while let Some(record) = records.try_next().await? {
+        let start = Position::from(usize::try_from(start)?);
+        let pos = record.position();
+
+        if pos > start {
+            return Ok(None);
+        }
+
+        let vcf_record = record.try_into_vcf_record(header)?;
+        if pos == start
+            && vcf_record.reference_bases() == &reference_bases.parse::<ReferenceBases>()?
+            && vcf_record.alternate_bases() == &alternate_bases.parse::<AlternateBases>()?
+        {
+            return Ok(Some(SequenceMatch::Counted(count_genotypes(&vcf_record)?)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Walk every record in `[start, end]`, using the record comparator appropriate for the format,
+/// and return the ones that pass the optional `reference_bases`/`alternate_bases`/`variant_type`
+/// filters. Only VCF and BCF carry the ref/alt alleles a range query matches against.
+async fn beacon_range_query<R>(
+    reader: R,
+    header: &SequenceHeader,
+    format: SequenceFormat,
+    start: u32,
+    end: u32,
+    reference_bases: Option<&str>,
+    alternate_bases: Option<&str>,
+    variant_type: Option<&str>,
+) -> Result<Vec<VariantMatch>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    match (format, header) {
+        (SequenceFormat::Vcf, SequenceHeader::Vcf(header)) => {
+            vcf_range_query(
+                reader,
+                header,
+                start,
+                end,
+                reference_bases,
+                alternate_bases,
+                variant_type,
+            )
+            .await
+        }
+        (SequenceFormat::Bcf, SequenceHeader::Vcf(header)) => {
+            bcf_range_query(
+                reader,
+                header,
+                start,
+                end,
+                reference_bases,
+                alternate_bases,
+                variant_type,
+            )
+            .await
+        }
+        (SequenceFormat::Bam, _) | (SequenceFormat::Cram, _) => {
+            Err(Error::from("range queries are not supported for BAM/CRAM inputs"))
+        }
+        _ => unreachable!("format and header kind are always constructed together"),
+    }
+}
+
+async fn vcf_range_query<R>(
+    reader: R,
+    header: &Header,
+    start: u32,
+    end: u32,
+    reference_bases: Option<&str>,
+    alternate_bases: Option<&str>,
+    variant_type: Option<&str>,
+) -> Result<Vec<VariantMatch>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut vcf_blocks = vcf::AsyncReader::new(bgzf::AsyncReader::new(reader));
+    let mut records = vcf_blocks.records(header);
+
+    let start = Position::from(usize::try_from(start)?);
+    let end = Position::from(usize::try_from(end)?);
+    let mut matches = Vec::new();
+
+    while let Some(record) = records.try_next().await? {
+        let pos = record.position();
+        if pos > end {
+            break;
+        }
+
+        if pos >= start {
+            matches.extend(match_variant(
+                &record,
+                reference_bases,
+                alternate_bases,
+                variant_type,
+            )?);
+        }
+    }
+
+    Ok(matches)
+}
+
+async fn bcf_range_query<R>(
+    reader: R,
+    header: &Header,
+    start: u32,
+    end: u32,
+    reference_bases: Option<&str>,
+    alternate_bases: Option<&str>,
+    variant_type: Option<&str>,
+) -> Result<Vec<VariantMatch>>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut bcf_blocks = bcf::AsyncReader::new(reader);
+    let mut records = bcf_blocks.records(header);
+
+    let start = Position::from(usize::try_from(start)?);
+    let end = Position::from(usize::try_from(end)?);
+    let mut matches = Vec::new();
+
+    while let Some(record) = records.try_next().await? {
+        let pos = record.position();
+        if pos > end {
+            break;
+        }
+
+        if pos >= start {
+            let vcf_record = record.try_into_vcf_record(header)?;
+            matches.extend(match_variant(
+                &vcf_record,
+                reference_bases,
+                alternate_bases,
+                variant_type,
+            )?);
+        }
+    }
+
+    Ok(matches)
+}
+
+/// Filter a candidate record against the optional `reference_bases`/`alternate_bases`/
+/// `variant_type` constraints of a range query, returning one match per ALT allele that
+/// passes. A multi-allelic site (`ALT=C,TG`) is reported as two independent matches rather
+/// than one opaque `"C,TG"` match, since each allele has its own type and either may be the
+/// one a caller's `alternate_bases` filter is actually looking for.
+fn match_variant(
+    record: &noodles_vcf::Record,
+    reference_bases: Option<&str>,
+    alternate_bases: Option<&str>,
+    variant_type: Option<&str>,
+) -> Result<Vec<VariantMatch>> {
+    if let Some(reference_bases) = reference_bases {
+        if record.reference_bases() != &reference_bases.parse::<ReferenceBases>()? {
+            return Ok(Vec::new());
+        }
+    }
+
+    let record_reference_bases = record.reference_bases().to_string();
+    let position = u32::try_from(usize::from(record.position()))?;
+
+    let mut matches = Vec::new();
+    for allele in record.alternate_bases().iter() {
+        let allele_bases = allele.to_string();
+
+        if let Some(alternate_bases) = alternate_bases {
+            if allele_bases != alternate_bases {
+                continue;
+            }
+        }
+
+        if let Some(variant_type) = variant_type {
+            if classify_variant(&record_reference_bases, &allele_bases) != variant_type {
+                continue;
+            }
+        }
+
+        matches.push(VariantMatch {
+            position,
+            reference_bases: record_reference_bases.clone(),
+            alternate_bases: allele_bases,
+        });
+    }
+
+    Ok(matches)
+}
+
+/// Classify a variant by comparing the lengths of its reference and alternate bases: an
+/// insertion is longer on the alternate allele, a deletion is longer on the reference allele,
+/// a single-base substitution is an SNV, and any other equal-length change is an MNV.
+fn classify_variant(reference_bases: &str, alternate_bases: &str) -> &'static str {
+    match (reference_bases.len(), alternate_bases.len()) {
+        (r, a) if r < a => "INS",
+        (r, a) if r > a => "DEL",
+        (1, 1) => "SNV",
+        _ => "MNV",
+    }
+}
+
+/// Count, across a matched VCF/BCF record's samples, how many carry at least one non-reference
+/// allele at the site (`variant_count`), out of how many have a non-missing genotype
+/// (`call_count`). A sample's `GT` is treated as missing if every allele is missing (`.`), and
+/// as a carrier if any allele index is greater than 0.
+fn count_genotypes(record: &noodles_vcf::Record) -> Result<MatchCounts> {
+    let mut counts = MatchCounts::default();
+
+    for genotype in record.genotypes().genotypes()? {
+        let alleles: Vec<_> = genotype.iter().collect();
+
+        if alleles.iter().all(|allele| allele.position().is_none()) {
+            continue;
+        }
+
+        counts.call_count += 1;
+
+        if alleles
+            .iter()
+            .any(|allele| allele.position().map_or(false, |position| position > 0))
+        {
+            counts.variant_count += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Walk a read's CIGAR from its `alignment_start`, and return the `len` bases of its aligned
+/// `sequence` that actually cover reference position `target`, or `None` if `target` isn't
+/// covered by a match/mismatch operation (e.g. it falls inside a deletion, or past the end of
+/// the read). This is what lets a caller compare the base(s) a read actually carries at a
+/// position against an expected allele, rather than searching the whole read sequence for a
+/// substring that could just as easily have matched anywhere else in it.
+fn aligned_bases_at(
+    cigar: &sam::record::Cigar,
+    sequence: &str,
+    alignment_start: core::Position,
+    target: core::Position,
+    len: usize,
+) -> Option<String> {
+    use sam::record::cigar::op::Kind;
+
+    let mut ref_pos = usize::from(alignment_start);
+    let mut query_pos = 0usize;
+    let target = usize::from(target);
+
+    for op in cigar.iter() {
+        let op_len = op.len();
+
+        match op.kind() {
+            Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch => {
+                if target >= ref_pos && target < ref_pos + op_len {
+                    let base_start = query_pos + (target - ref_pos);
+                    return sequence.get(base_start..base_start + len).map(str::to_string);
+                }
+                ref_pos += op_len;
+                query_pos += op_len;
+            }
+            Kind::Deletion | Kind::Skip => {
+                if target >= ref_pos && target < ref_pos + op_len {
+                    // `target` falls inside a deleted/skipped region: this read carries no
+                    // base there to compare against either allele.
+                    return None;
+                }
+                ref_pos += op_len;
+            }
+            Kind::Insertion | Kind::SoftClip => query_pos += op_len,
+            Kind::HardClip | Kind::Padding => {}
         }
     }
 
-    Ok(false)
+    None
+}
+
+/// A sequence match for alignment formats (BAM/CRAM) is a read whose start position coincides
+/// with the query, carrying `alternate_bases` (rather than `reference_bases`) over the
+/// `reference_bases`-length window at that position in its CIGAR-aligned sequence — i.e. the
+/// read shows the variant allele there, not just the reference one.
+async fn read_sequence_query<R>(
+    reader: R,
+    format: SequenceFormat,
+    header: &sam::Header,
+    start: u32,
+    reference_bases: &str,
+    alternate_bases: &str,
+) -> Result<bool>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let start = core::Position::new(usize::try_from(start)?)
+        .ok_or_else(|| Error::from("start position must be non-zero"))?;
+
+    match format {
+        SequenceFormat::Bam => {
+            let mut bam_reader = bam::AsyncReader::new(bgzf::AsyncReader::new(reader));
+            let mut records = bam_reader.records(header);
+
+            while let Some(record) = records.try_next().await? {
+                if let Some(pos) = record.alignment_start() {
+                    if pos > start {
+                        return Ok(false);
+                    }
+
+                    if pos == start {
+                        let sequence = record.sequence().to_string();
+                        if aligned_bases_at(record.cigar(), &sequence, pos, start, reference_bases.len())
+                            .as_deref()
+                            == Some(alternate_bases)
+                        {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+
+            Ok(false)
+        }
+        SequenceFormat::Cram => {
+            let mut cram_reader = cram::AsyncReader::new(reader);
+            let mut records = cram_reader.records(header).await;
+
+            while let Some(record) = records.try_next().await? {
+                if let Some(pos) = record.alignment_start() {
+                    if pos > start {
+                        return Ok(false);
+                    }
+
+                    if pos == start {
+                        let sequence = record.sequence().to_string();
+                        if aligned_bases_at(record.cigar(), &sequence, pos, start, reference_bases.len())
+                            .as_deref()
+                            == Some(alternate_bases)
+                        {
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+
+            Ok(false)
+        }
+        _ => unreachable!("only called for Bam/Cram"),
+    }
 }
 
 #[cfg(test)]
@@ -188,22 +1183,44 @@ mod tests {
     // chr1    1220751 T       C       0010111111
     // chr1    1236037 C       T       1100011111
 
+    fn s3_storage() -> S3Storage {
+        S3Storage::new(aws_sdk_s3::Client::new(
+            &aws_config::SdkConfig::builder().build(),
+        ))
+    }
+
+    /// `LocalStorage` rooted at `tests/fixtures/vcf-data`, where each sample's bundled,
+    /// bgzipped-and-tabix-indexed VCF lives under a "bucket" directory named after it. Lets
+    /// the same queries the `umccr-10g-data-dev` tests run also run offline in CI.
+    fn local_storage() -> LocalStorage {
+        LocalStorage::new(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/vcf-data"),
+        )
+    }
+
     #[tokio::test]
     async fn test_where_variant_should_be_found() {
-        let r = beacon_handler(LambdaEvent::new(
-            SequenceQueryRequest {
-                vcf_bucket: "umccr-10g-data-dev".to_string(),
-                vcf_key: "HG00174/HG00174.hard-filtered.vcf.gz".to_string(),
-                vcf_index_bucket: "umccr-10g-data-dev".to_string(),
-                vcf_index_key: "HG00174/HG00174.hard-filtered.vcf.gz.tbi".to_string(),
-                // chr1    1220751 T       C       0010111111
-                reference_name: "chr1".to_string(),
-                start: 1220751,
-                reference_bases: "T".to_string(),
-                alternate_bases: "C".to_string(),
-            },
-            Context::default(),
-        ))
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "umccr-10g-data-dev".to_string(),
+                    vcf_key: "HG00174/HG00174.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "umccr-10g-data-dev".to_string(),
+                    vcf_index_key: "HG00174/HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    // chr1    1220751 T       C       0010111111
+                    reference_name: "chr1".to_string(),
+                    start: 1220751,
+                    end: None,
+                    reference_bases: Some("T".to_string()),
+                    alternate_bases: Some("C".to_string()),
+                    variant_type: None,
+                    count: false,
+                },
+                Context::default(),
+            ),
+            s3_storage(),
+        )
         .await;
 
         assert!(r.unwrap().found, "Expected variant was not found");
@@ -211,22 +1228,402 @@ mod tests {
 
     #[tokio::test]
     async fn test_where_variant_should_not_be_found() {
-        let r = beacon_handler(LambdaEvent::new(
-            SequenceQueryRequest {
-                vcf_bucket: "umccr-10g-data-dev".to_string(),
-                vcf_key: "HG00096/HG00096.hard-filtered.vcf.gz".to_string(),
-                vcf_index_bucket: "umccr-10g-data-dev".to_string(),
-                vcf_index_key: "HG00096/HG00096.hard-filtered.vcf.gz.tbi".to_string(),
-                // chr1    1220751 T       C       0010111111
-                reference_name: "chr1".to_string(),
-                start: 1220751,
-                reference_bases: "T".to_string(),
-                alternate_bases: "C".to_string(),
-            },
-            Context::default(),
-        ))
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "umccr-10g-data-dev".to_string(),
+                    vcf_key: "HG00096/HG00096.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "umccr-10g-data-dev".to_string(),
+                    vcf_index_key: "HG00096/HG00096.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    // chr1    1220751 T       C       0010111111
+                    reference_name: "chr1".to_string(),
+                    start: 1220751,
+                    end: None,
+                    reference_bases: Some("T".to_string()),
+                    alternate_bases: Some("C".to_string()),
+                    variant_type: None,
+                    count: false,
+                },
+                Context::default(),
+            ),
+            s3_storage(),
+        )
         .await;
 
         assert!(!r.unwrap().found, "Unexpected variant was found");
     }
+
+    #[tokio::test]
+    async fn test_batch_handler_groups_queries_by_file() {
+        let r = beacon_batch_handler(
+            LambdaEvent::new(
+                BatchSequenceQueryRequest {
+                    queries: vec![
+                        SequenceQueryRequest {
+                            vcf_bucket: "umccr-10g-data-dev".to_string(),
+                            vcf_key: "HG00174/HG00174.hard-filtered.vcf.gz".to_string(),
+                            vcf_index_bucket: "umccr-10g-data-dev".to_string(),
+                            vcf_index_key: "HG00174/HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                            format: None,
+                            // chr1    1220751 T       C       0010111111
+                            reference_name: "chr1".to_string(),
+                            start: 1220751,
+                            end: None,
+                            reference_bases: Some("T".to_string()),
+                            alternate_bases: Some("C".to_string()),
+                            variant_type: None,
+                            count: false,
+                        },
+                        // Same file as the query above, different site: should be answered from
+                        // the same header/index fetch and merged byte-range download.
+                        SequenceQueryRequest {
+                            vcf_bucket: "umccr-10g-data-dev".to_string(),
+                            vcf_key: "HG00174/HG00174.hard-filtered.vcf.gz".to_string(),
+                            vcf_index_bucket: "umccr-10g-data-dev".to_string(),
+                            vcf_index_key: "HG00174/HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                            format: None,
+                            // chr1    1135738 G       C       1111110100
+                            reference_name: "chr1".to_string(),
+                            start: 1135738,
+                            end: None,
+                            reference_bases: Some("G".to_string()),
+                            alternate_bases: Some("C".to_string()),
+                            variant_type: None,
+                            count: false,
+                        },
+                        // A different file, in its own group, where the variant isn't present.
+                        SequenceQueryRequest {
+                            vcf_bucket: "umccr-10g-data-dev".to_string(),
+                            vcf_key: "HG00096/HG00096.hard-filtered.vcf.gz".to_string(),
+                            vcf_index_bucket: "umccr-10g-data-dev".to_string(),
+                            vcf_index_key: "HG00096/HG00096.hard-filtered.vcf.gz.tbi".to_string(),
+                            format: None,
+                            reference_name: "chr1".to_string(),
+                            start: 1220751,
+                            end: None,
+                            reference_bases: Some("T".to_string()),
+                            alternate_bases: Some("C".to_string()),
+                            variant_type: None,
+                            count: false,
+                        },
+                    ],
+                },
+                Context::default(),
+            ),
+            s3_storage(),
+        )
+        .await;
+
+        let results = r.unwrap().results;
+        assert!(results[0].found, "Expected variant was not found");
+        assert!(results[1].found, "Expected variant was not found");
+        assert!(!results[2].found, "Unexpected variant was found");
+    }
+
+    #[tokio::test]
+    async fn test_handover_url_is_returned_for_a_found_variant() {
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "umccr-10g-data-dev".to_string(),
+                    vcf_key: "HG00174/HG00174.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "umccr-10g-data-dev".to_string(),
+                    vcf_index_key: "HG00174/HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    // chr1    1220751 T       C       0010111111
+                    reference_name: "chr1".to_string(),
+                    start: 1220751,
+                    end: None,
+                    reference_bases: Some("T".to_string()),
+                    alternate_bases: Some("C".to_string()),
+                    variant_type: None,
+                    count: false,
+                },
+                Context::default(),
+            ),
+            s3_storage(),
+        )
+        .await;
+
+        let response = r.unwrap();
+        assert!(response.found, "Expected variant was not found");
+        assert!(
+            response.handover.is_some(),
+            "Expected a pre-signed handover URL for a found variant"
+        );
+    }
+
+    // The tests above require the live `umccr-10g-data-dev` bucket. The same queries, run
+    // against the bundled fixtures in `tests/fixtures/vcf-data` through `LocalStorage`, cover
+    // the same code paths offline and in CI.
+
+    #[tokio::test]
+    async fn test_local_storage_where_variant_should_be_found() {
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "HG00174".to_string(),
+                    vcf_key: "HG00174.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "HG00174".to_string(),
+                    vcf_index_key: "HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    reference_name: "chr1".to_string(),
+                    start: 1220751,
+                    end: None,
+                    reference_bases: Some("T".to_string()),
+                    alternate_bases: Some("C".to_string()),
+                    variant_type: None,
+                    count: false,
+                },
+                Context::default(),
+            ),
+            local_storage(),
+        )
+        .await;
+
+        assert!(r.unwrap().found, "Expected variant was not found");
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_where_variant_should_not_be_found() {
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "HG00096".to_string(),
+                    vcf_key: "HG00096.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "HG00096".to_string(),
+                    vcf_index_key: "HG00096.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    reference_name: "chr1".to_string(),
+                    start: 1220751,
+                    end: None,
+                    reference_bases: Some("T".to_string()),
+                    alternate_bases: Some("C".to_string()),
+                    variant_type: None,
+                    count: false,
+                },
+                Context::default(),
+            ),
+            local_storage(),
+        )
+        .await;
+
+        assert!(!r.unwrap().found, "Unexpected variant was found");
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_count_reports_carrier_and_call_counts() {
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "HG00174".to_string(),
+                    vcf_key: "HG00174.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "HG00174".to_string(),
+                    vcf_index_key: "HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    // chr1    1220751 T       C       0/1
+                    reference_name: "chr1".to_string(),
+                    start: 1220751,
+                    end: None,
+                    reference_bases: Some("T".to_string()),
+                    alternate_bases: Some("C".to_string()),
+                    variant_type: None,
+                    count: true,
+                },
+                Context::default(),
+            ),
+            local_storage(),
+        )
+        .await;
+
+        let response = r.unwrap();
+        assert!(response.found, "Expected variant was not found");
+        assert_eq!(response.variant_count, Some(1));
+        assert_eq!(response.call_count, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_local_storage_range_query_spans_both_fixture_sites() {
+        let r = beacon_handler(
+            LambdaEvent::new(
+                SequenceQueryRequest {
+                    vcf_bucket: "HG00174".to_string(),
+                    vcf_key: "HG00174.hard-filtered.vcf.gz".to_string(),
+                    vcf_index_bucket: "HG00174".to_string(),
+                    vcf_index_key: "HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                    format: None,
+                    // chr1    1135738 G       C       1/1
+                    // chr1    1220751 T       C       0/1
+                    reference_name: "chr1".to_string(),
+                    start: 1135738,
+                    end: Some(1220751),
+                    reference_bases: None,
+                    alternate_bases: None,
+                    variant_type: None,
+                    count: false,
+                },
+                Context::default(),
+            ),
+            local_storage(),
+        )
+        .await;
+
+        let matches = r.unwrap().matches.expect("range query should report matches");
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].position, 1135738);
+        assert_eq!(matches[1].position, 1220751);
+    }
+
+    #[tokio::test]
+    async fn test_batch_handler_reports_a_group_failure_without_losing_other_results() {
+        let r = beacon_batch_handler(
+            LambdaEvent::new(
+                BatchSequenceQueryRequest {
+                    queries: vec![
+                        SequenceQueryRequest {
+                            vcf_bucket: "HG00174".to_string(),
+                            vcf_key: "HG00174.hard-filtered.vcf.gz".to_string(),
+                            vcf_index_bucket: "HG00174".to_string(),
+                            vcf_index_key: "HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+                            format: None,
+                            // chr1    1220751 T       C       0/1
+                            reference_name: "chr1".to_string(),
+                            start: 1220751,
+                            end: None,
+                            reference_bases: Some("T".to_string()),
+                            alternate_bases: Some("C".to_string()),
+                            variant_type: None,
+                            count: false,
+                        },
+                        // A different file, on its own, with `end` set — unsupported in batch
+                        // mode. This group should fail without wiping out the result above.
+                        SequenceQueryRequest {
+                            vcf_bucket: "HG00096".to_string(),
+                            vcf_key: "HG00096.hard-filtered.vcf.gz".to_string(),
+                            vcf_index_bucket: "HG00096".to_string(),
+                            vcf_index_key: "HG00096.hard-filtered.vcf.gz.tbi".to_string(),
+                            format: None,
+                            reference_name: "chr1".to_string(),
+                            start: 1135738,
+                            end: Some(1220751),
+                            reference_bases: None,
+                            alternate_bases: None,
+                            variant_type: None,
+                            count: false,
+                        },
+                    ],
+                },
+                Context::default(),
+            ),
+            local_storage(),
+        )
+        .await;
+
+        let results = r.unwrap().results;
+        assert!(results[0].found, "Expected variant was not found");
+        assert!(results[0].error.is_none());
+        assert!(!results[1].found);
+        assert!(
+            results[1].error.is_some(),
+            "Expected the unsupported range query to report a per-result error"
+        );
+    }
+
+    #[test]
+    fn test_match_variant_reports_one_match_per_alt_allele() {
+        let record = noodles_vcf::Record::builder()
+            .set_chromosome("chr1".parse().unwrap())
+            .set_position(Position::from(100))
+            .set_reference_bases("C".parse::<ReferenceBases>().unwrap())
+            .set_alternate_bases("C,TG".parse::<AlternateBases>().unwrap())
+            .build()
+            .unwrap();
+
+        let matches = match_variant(&record, None, None, None).unwrap();
+
+        assert_eq!(matches.len(), 2, "a multi-allelic site should report one match per ALT allele");
+        assert_eq!(matches[0].alternate_bases, "C");
+        assert_eq!(matches[1].alternate_bases, "TG");
+        // REF=C/ALT=C is an MNV-length no-op at length 1 (SNV), REF=C/ALT=TG is an insertion:
+        // classifying the whole `"C,TG"` ALT field as one unit (length 4) would misclassify both.
+        assert_eq!(classify_variant("C", "C"), "SNV");
+        assert_eq!(classify_variant("C", "TG"), "INS");
+    }
+
+    #[test]
+    fn test_match_variant_filters_by_a_single_alt_allele() {
+        let record = noodles_vcf::Record::builder()
+            .set_chromosome("chr1".parse().unwrap())
+            .set_position(Position::from(100))
+            .set_reference_bases("C".parse::<ReferenceBases>().unwrap())
+            .set_alternate_bases("C,TG".parse::<AlternateBases>().unwrap())
+            .build()
+            .unwrap();
+
+        let matches = match_variant(&record, None, Some("TG"), None).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].alternate_bases, "TG");
+    }
+
+    fn query_for(reference_name: &str) -> SequenceQueryRequest {
+        SequenceQueryRequest {
+            vcf_bucket: "HG00174".to_string(),
+            vcf_key: "HG00174.hard-filtered.vcf.gz".to_string(),
+            vcf_index_bucket: "HG00174".to_string(),
+            vcf_index_key: "HG00174.hard-filtered.vcf.gz.tbi".to_string(),
+            format: None,
+            reference_name: reference_name.to_string(),
+            start: 1220751,
+            end: None,
+            reference_bases: Some("T".to_string()),
+            alternate_bases: Some("C".to_string()),
+            variant_type: None,
+            count: false,
+        }
+    }
+
+    #[test]
+    fn test_is_same_file_keeps_different_reference_names_in_separate_groups() {
+        // Same bucket/key/index, different contig: must not be grouped, since the point-query
+        // comparators never check `record.chromosome()` — merging their byte ranges would let
+        // a coincidentally identical position/REF/ALT on one contig answer the other's query.
+        assert!(!is_same_file(&query_for("chr1"), &query_for("chr2")));
+        assert!(is_same_file(&query_for("chr1"), &query_for("chr1")));
+    }
+
+    #[test]
+    fn test_aligned_bases_at_reads_the_base_covering_the_target_position() {
+        let cigar = "10M".parse::<sam::record::Cigar>().unwrap();
+        let alignment_start = core::Position::new(100).unwrap();
+        let sequence = "ACGTACGTAC";
+
+        let observed = aligned_bases_at(&cigar, sequence, alignment_start, alignment_start, 1);
+        assert_eq!(observed.as_deref(), Some("A"));
+
+        let target = core::Position::new(103).unwrap();
+        let observed = aligned_bases_at(&cigar, sequence, alignment_start, target, 1);
+        assert_eq!(observed.as_deref(), Some("T"));
+    }
+
+    #[test]
+    fn test_aligned_bases_at_returns_none_for_a_position_inside_a_deletion() {
+        // 5M covers 100-104, 3D covers 105-107 with no read bases, 5M covers 108-112.
+        let cigar = "5M3D5M".parse::<sam::record::Cigar>().unwrap();
+        let alignment_start = core::Position::new(100).unwrap();
+        let sequence = "AAAAACCCCC";
+
+        let inside_deletion = core::Position::new(106).unwrap();
+        assert_eq!(
+            aligned_bases_at(&cigar, sequence, alignment_start, inside_deletion, 1),
+            None
+        );
+
+        let after_deletion = core::Position::new(108).unwrap();
+        assert_eq!(
+            aligned_bases_at(&cigar, sequence, alignment_start, after_deletion, 1).as_deref(),
+            Some("C")
+        );
+    }
 }
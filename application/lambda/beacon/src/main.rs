@@ -5,7 +5,7 @@ use tokio::io;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::{fmt, EnvFilter, Registry};
 
-use beacon::beacon_handler;
+use beacon::{beacon_dispatch_handler, S3Storage, S3StorageConfig};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -20,7 +20,12 @@ async fn main() -> Result<(), Error> {
         )
     })?;
 
-    let handler = service_fn(beacon_handler);
+    let storage = S3Storage::from_config(S3StorageConfig::from_env()).await;
+
+    let handler = service_fn(move |event| {
+        let storage = storage.clone();
+        async move { beacon_dispatch_handler(event, storage).await }
+    });
     lambda_runtime::run(handler).await?;
 
     Ok(())
@@ -0,0 +1,388 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use aws_config::environment::EnvironmentVariableCredentialsProvider;
+use aws_config::imds::credentials::ImdsCredentialsProvider;
+use aws_config::meta::credentials::CredentialsProviderChain;
+use aws_config::profile::ProfileFileCredentialsProvider;
+use aws_config::retry::RetryConfig;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::Client;
+use bytes::Bytes;
+use htsget_search::storage::aws::AwsS3Storage;
+use htsget_search::storage::local::LocalStorage as HtsgetLocalStorage;
+use htsget_search::RegexResolver;
+
+use crate::Result;
+
+/// Default maximum number of attempts for a single S3 request, used unless overridden by
+/// `BEACON_S3_MAX_ATTEMPTS`.
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// How long a pre-signed handover URL remains valid for.
+const PRESIGNED_URL_EXPIRY: Duration = Duration::from_secs(300);
+
+/// A key that was expected to exist in storage, but doesn't. Returned instead of presigning a
+/// handover URL for a file that isn't actually there.
+#[derive(Debug)]
+pub struct KeyNotFound {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl std::fmt::Display for KeyNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "key not found: s3://{}/{}", self.bucket, self.key)
+    }
+}
+
+impl std::error::Error for KeyNotFound {}
+
+/// Storage backend for the beacon's own reads: fetching an index file in full, and fetching
+/// a byte range of the data file it indexes. This is separate from the `htsget_search::storage`
+/// backend, which the format-specific `Search` implementations use internally to work out
+/// which byte ranges to ask for in the first place.
+///
+/// Implementing this trait against a new backend, and returning the matching `htsget_search`
+/// storage via `htsget_storage`, is enough to run the beacon against it.
+#[async_trait::async_trait]
+pub trait BeaconStorage: Clone + Send + Sync + 'static {
+    /// The `htsget_search` storage backend that matches this one, used to construct the
+    /// format-specific `Search` implementations.
+    type HtsgetStorage: htsget_search::storage::Storage + Send + Sync + 'static;
+
+    /// Fetch the full contents of an index file (.tbi/.csi/.crai).
+    async fn get_index_bytes(&self, bucket: &str, key: &str) -> Result<Bytes>;
+
+    /// Fetch a byte range `[start, end]` of a data file. `end` is inclusive; `None` means
+    /// read to the end of the file.
+    async fn get_range(&self, bucket: &str, key: &str, start: u64, end: Option<u64>)
+        -> Result<Bytes>;
+
+    /// Whether a key exists, used to guard against presigning a handover URL for a file that
+    /// isn't actually there.
+    async fn key_exists(&self, bucket: &str, key: &str) -> Result<bool>;
+
+    /// Build a time-limited pre-signed GET URL for a byte range `[start, end]` of a data file,
+    /// if this backend has a notion of pre-signed URLs. Returns `None` for backends that don't
+    /// (e.g. local storage).
+    async fn presigned_range_url(
+        &self,
+        bucket: &str,
+        key: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Option<String>>;
+
+    /// Build the `htsget_search` storage backend rooted at a given bucket.
+    fn htsget_storage(&self, bucket: String) -> Result<Self::HtsgetStorage>;
+}
+
+/// A `BeaconStorage` backed by S3, or an S3-compatible store.
+#[derive(Clone)]
+pub struct S3Storage {
+    client: Client,
+}
+
+impl S3Storage {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Build an `S3Storage` from a `S3StorageConfig`, using an explicit
+    /// environment -> profile -> IMDS credentials chain, a configurable retry policy, and an
+    /// optional custom endpoint. This is what lets the beacon target a self-hosted
+    /// S3-compatible store (Garage, MinIO) instead of only AWS S3.
+    pub async fn from_config(config: S3StorageConfig) -> Self {
+        let credentials_provider = CredentialsProviderChain::first_try(
+            "Environment",
+            EnvironmentVariableCredentialsProvider::new(),
+        )
+        .or_else("Profile", ProfileFileCredentialsProvider::builder().build())
+        .or_else("IMDS", ImdsCredentialsProvider::builder().build());
+
+        let retry_config =
+            RetryConfig::standard().with_max_attempts(config.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS));
+
+        let mut loader = aws_config::from_env()
+            .credentials_provider(credentials_provider)
+            .retry_config(retry_config);
+
+        if let Some(endpoint_url) = &config.endpoint_url {
+            loader = loader.endpoint_url(endpoint_url);
+        }
+
+        let sdk_config = loader.load().await;
+
+        let mut s3_config = aws_sdk_s3::config::Builder::from(&sdk_config);
+        if config.force_path_style {
+            s3_config = s3_config.force_path_style(true);
+        }
+
+        Self::new(Client::from_conf(s3_config.build()))
+    }
+}
+
+/// Configuration for building the S3 client behind `S3Storage`. Lets the beacon be pointed at
+/// a self-hosted S3-compatible store, and hardens reads against transient throttling during
+/// the range-fetch fan-out.
+#[derive(Debug, Clone, Default)]
+pub struct S3StorageConfig {
+    /// Overrides the S3 endpoint, e.g. for a Garage or MinIO deployment.
+    pub endpoint_url: Option<String>,
+    /// Required by most S3-compatible stores, which don't support virtual-hosted-style
+    /// addressing.
+    pub force_path_style: bool,
+    /// Maximum number of attempts per S3 request, defaults to `DEFAULT_MAX_ATTEMPTS`.
+    pub max_attempts: Option<u32>,
+}
+
+impl S3StorageConfig {
+    /// Build the config from the `BEACON_S3_*` environment variables, all of which are
+    /// optional.
+    pub fn from_env() -> Self {
+        Self {
+            endpoint_url: env::var("BEACON_S3_ENDPOINT_URL").ok(),
+            force_path_style: env::var("BEACON_S3_FORCE_PATH_STYLE")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(false),
+            max_attempts: env::var("BEACON_S3_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|value| value.parse().ok()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl BeaconStorage for S3Storage {
+    type HtsgetStorage = AwsS3Storage;
+
+    async fn get_index_bytes(&self, bucket: &str, key: &str) -> Result<Bytes> {
+        let response = self.client.get_object().bucket(bucket).key(key).send().await?;
+
+        Ok(response.body.collect().await?.into_bytes())
+    }
+
+    async fn get_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Bytes> {
+        let range = match end {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => format!("bytes={}-", start),
+        };
+
+        let response = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .range(range)
+            .send()
+            .await?;
+
+        Ok(response.body.collect().await?.into_bytes())
+    }
+
+    async fn key_exists(&self, bucket: &str, key: &str) -> Result<bool> {
+        match self.client.head_object().bucket(bucket).key(key).send().await {
+            Ok(_) => Ok(true),
+            Err(err) => match err.as_service_error() {
+                Some(err) if err.is_not_found() => Ok(false),
+                _ => Err(err.into()),
+            },
+        }
+    }
+
+    async fn presigned_range_url(
+        &self,
+        bucket: &str,
+        key: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Option<String>> {
+        let range = match end {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => format!("bytes={}-", start),
+        };
+
+        let presigning_config = PresigningConfig::expires_in(PRESIGNED_URL_EXPIRY)?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .range(range)
+            .presigned(presigning_config)
+            .await?;
+
+        Ok(Some(presigned.uri().to_string()))
+    }
+
+    fn htsget_storage(&self, bucket: String) -> Result<Self::HtsgetStorage> {
+        Ok(AwsS3Storage::new(self.client.clone(), bucket, RegexResolver::default()))
+    }
+}
+
+/// A `BeaconStorage` backed by the local filesystem, rooted at a base directory. Each
+/// `bucket` is a subdirectory of the base path, so fixture data can be laid out the same way
+/// a real bucket would be and used in tests without a live S3 connection.
+#[derive(Clone)]
+pub struct LocalStorage {
+    base_path: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self { base_path }
+    }
+
+    /// Reject a caller-supplied path component that could escape `base_path`: an absolute
+    /// path discards the base entirely when joined (`PathBuf::join` takes over on an absolute
+    /// operand), and a `..` component walks back out of it.
+    fn reject_path_escape(component: &str) -> Result<()> {
+        let path = Path::new(component);
+
+        if path.is_absolute() {
+            return Err(Error::from(format!(
+                "storage path component must not be absolute: {component}"
+            )));
+        }
+
+        if path.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+            return Err(Error::from(format!(
+                "storage path component must not contain '..': {component}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Resolve a `bucket`/`key` pair to a path under `base_path`, rejecting either component
+    /// if it would otherwise escape it.
+    fn path(&self, bucket: &str, key: &str) -> Result<PathBuf> {
+        Self::reject_path_escape(bucket)?;
+        Self::reject_path_escape(key)?;
+
+        Ok(self.base_path.join(bucket).join(key))
+    }
+
+    /// Resolve a `bucket` to a directory under `base_path`, rejecting it if it would otherwise
+    /// escape it.
+    fn bucket_path(&self, bucket: &str) -> Result<PathBuf> {
+        Self::reject_path_escape(bucket)?;
+
+        Ok(self.base_path.join(bucket))
+    }
+}
+
+#[async_trait::async_trait]
+impl BeaconStorage for LocalStorage {
+    type HtsgetStorage = HtsgetLocalStorage;
+
+    async fn get_index_bytes(&self, bucket: &str, key: &str) -> Result<Bytes> {
+        Ok(Bytes::from(tokio::fs::read(self.path(bucket, key)?).await?))
+    }
+
+    async fn get_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<Bytes> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let mut file = tokio::fs::File::open(self.path(bucket, key)?).await?;
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+
+        let bytes = match end {
+            Some(end) => {
+                let mut buf = vec![0u8; usize::try_from(end - start + 1)?];
+                file.read_exact(&mut buf).await?;
+                buf
+            }
+            None => {
+                let mut buf = Vec::new();
+                file.read_to_end(&mut buf).await?;
+                buf
+            }
+        };
+
+        Ok(Bytes::from(bytes))
+    }
+
+    async fn key_exists(&self, bucket: &str, key: &str) -> Result<bool> {
+        Ok(tokio::fs::try_exists(self.path(bucket, key)?).await?)
+    }
+
+    async fn presigned_range_url(
+        &self,
+        _bucket: &str,
+        _key: &str,
+        _start: u64,
+        _end: Option<u64>,
+    ) -> Result<Option<String>> {
+        // Local storage has no notion of a pre-signed URL; callers fall back to not emitting
+        // a handover.
+        Ok(None)
+    }
+
+    fn htsget_storage(&self, bucket: String) -> Result<Self::HtsgetStorage> {
+        Ok(HtsgetLocalStorage::new(self.bucket_path(&bucket)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `env::set_var`/`remove_var` act on process-global state, so this is kept as a single
+    // test rather than split across several that could interleave under parallel test
+    // execution and race on the same `BEACON_S3_*` keys.
+    #[test]
+    fn test_s3_storage_config_from_env() {
+        env::remove_var("BEACON_S3_ENDPOINT_URL");
+        env::remove_var("BEACON_S3_FORCE_PATH_STYLE");
+        env::remove_var("BEACON_S3_MAX_ATTEMPTS");
+
+        let config = S3StorageConfig::from_env();
+        assert_eq!(config.endpoint_url, None);
+        assert!(!config.force_path_style, "should default to false when unset");
+        assert_eq!(config.max_attempts, None, "DEFAULT_MAX_ATTEMPTS is applied at call time, not here");
+
+        env::set_var("BEACON_S3_ENDPOINT_URL", "http://localhost:3900");
+        env::set_var("BEACON_S3_FORCE_PATH_STYLE", "1");
+        env::set_var("BEACON_S3_MAX_ATTEMPTS", "7");
+
+        let config = S3StorageConfig::from_env();
+        assert_eq!(config.endpoint_url.as_deref(), Some("http://localhost:3900"));
+        assert!(config.force_path_style);
+        assert_eq!(config.max_attempts, Some(7));
+
+        env::set_var("BEACON_S3_FORCE_PATH_STYLE", "false");
+        let config = S3StorageConfig::from_env();
+        assert!(!config.force_path_style, "only \"true\"/\"1\" should enable force_path_style");
+
+        env::remove_var("BEACON_S3_ENDPOINT_URL");
+        env::remove_var("BEACON_S3_FORCE_PATH_STYLE");
+        env::remove_var("BEACON_S3_MAX_ATTEMPTS");
+    }
+
+    #[test]
+    fn test_local_storage_path_rejects_escapes_out_of_base_path() {
+        let storage = LocalStorage::new(PathBuf::from("/base"));
+
+        assert!(storage.path("HG00174", "/etc/passwd").is_err());
+        assert!(storage.path("/etc", "passwd").is_err());
+        assert!(storage.path("HG00174", "../../etc/passwd").is_err());
+        assert!(storage.path("..", "HG00174.hard-filtered.vcf.gz").is_err());
+
+        let path = storage.path("HG00174", "HG00174.hard-filtered.vcf.gz").unwrap();
+        assert_eq!(path, PathBuf::from("/base/HG00174/HG00174.hard-filtered.vcf.gz"));
+    }
+}